@@ -0,0 +1,8 @@
+//! Headless WebDriver runner for `#[wasm_bindgen_test]` suites.
+//!
+//! This crate has no public API of its own; it's the binary that drives a
+//! browser through a test suite, servicing the DOM protocols the suite's
+//! wasm side (`wasm-bindgen-test`) uses to ask for things the wasm sandbox
+//! itself can't do, like taking screenshots.
+
+pub mod screenshot_assert;
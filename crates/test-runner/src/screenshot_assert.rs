@@ -0,0 +1,263 @@
+//! Runner-side half of `screenshot_assert`/`screenshot_assert_with_tolerance`.
+//!
+//! The wasm side (see `wasm_bindgen_test::rt::browser::screenshot_assert_with_tolerance`)
+//! writes a request to the page's `#__wbgtest_screenshot_assert` element and
+//! polls until it's cleared; this module is what the headless runner's
+//! WebDriver loop calls once it notices that element is non-empty.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A parsed `#__wbgtest_screenshot_assert` request: where to save the fresh
+/// screenshot, which committed baseline to compare it against, and the
+/// tolerances to compare with.
+///
+/// Mirrors the `{path}\n{baseline}\n{tolerance}\n{max_diff_ratio}` format
+/// written by `screenshot_assert_with_tolerance`.
+pub struct Request {
+    pub path: String,
+    pub baseline: String,
+    pub tolerance: f64,
+    pub max_diff_ratio: f64,
+}
+
+impl Request {
+    /// Parses a request written to `#__wbgtest_screenshot_assert`.
+    pub fn parse(text: &str) -> Option<Request> {
+        let mut lines = text.lines();
+        let path = lines.next()?.to_string();
+        let baseline = lines.next()?.to_string();
+        let tolerance = lines.next()?.parse().ok()?;
+        let max_diff_ratio = lines.next()?.parse().ok()?;
+        Some(Request {
+            path,
+            baseline,
+            tolerance,
+            max_diff_ratio,
+        })
+    }
+}
+
+/// Handles a screenshot-assert request once the runner has saved the fresh
+/// screenshot to `request.path` via WebDriver, returning the text to write
+/// back to `#__wbgtest_screenshot_assert_result`: `"OK"`, or the path of a
+/// written diff image.
+///
+/// If `UPDATE_EXPECT` is set in the environment, `request.baseline` is
+/// overwritten with the fresh screenshot instead of being compared against,
+/// and this always returns `"OK"`.
+pub fn handle(request: &Request) -> io::Result<String> {
+    if std::env::var_os("UPDATE_EXPECT").is_some() {
+        fs::copy(&request.path, &request.baseline)?;
+        return Ok("OK".to_string());
+    }
+
+    let actual = decode_png(Path::new(&request.path))?;
+    let baseline = decode_png(Path::new(&request.baseline))?;
+
+    match diff(&actual, &baseline, request.tolerance, request.max_diff_ratio) {
+        Some(diff_image) => {
+            let diff_path = diff_image_path(Path::new(&request.baseline));
+            write_png(&diff_path, &diff_image)?;
+            Ok(diff_path.display().to_string())
+        }
+        None => Ok("OK".to_string()),
+    }
+}
+
+/// A decoded RGBA image.
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+fn decode_png(path: &Path) -> io::Result<Image> {
+    let file = fs::File::open(path)?;
+    let decoder = png::Decoder::new(file);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let pixels = buf[..info.buffer_size()]
+        .chunks_exact(4)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
+        .collect();
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn write_png(path: &Path, image: &Image) -> io::Result<()> {
+    let file = fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(file, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let data: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+    writer
+        .write_image_data(&data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The full `0..=1020` range a pixel's summed RGBA delta is measured
+/// against: four channels, each `0..=255`.
+const CHANNEL_DELTA_RANGE: f64 = 255.0 * 4.0;
+
+/// Color a differing pixel is highlighted in on the diff image.
+const DIFF_HIGHLIGHT: [u8; 4] = [255, 0, 255, 255];
+
+/// Compares `actual` against `baseline`, returning a diff image (differing
+/// pixels highlighted in magenta over a dimmed copy of `baseline`) if they
+/// diverge by more than `tolerance`/`max_diff_ratio` allow, or `None` if
+/// they match closely enough.
+///
+/// A pixel counts as "different" once the summed absolute delta of its RGBA
+/// channels exceeds `tolerance` as a fraction of `CHANNEL_DELTA_RANGE`. The
+/// comparison fails -- and a diff image is produced -- once the fraction of
+/// differing pixels exceeds `max_diff_ratio`, or if the images aren't even
+/// the same size.
+fn diff(actual: &Image, baseline: &Image, tolerance: f64, max_diff_ratio: f64) -> Option<Image> {
+    if actual.width != baseline.width || actual.height != baseline.height {
+        let pixels = baseline.pixels.iter().map(|_| DIFF_HIGHLIGHT).collect();
+        return Some(Image {
+            width: baseline.width,
+            height: baseline.height,
+            pixels,
+        });
+    }
+
+    let threshold = tolerance * CHANNEL_DELTA_RANGE;
+    let mut differing = 0usize;
+    let mut pixels = Vec::with_capacity(baseline.pixels.len());
+
+    for (a, b) in actual.pixels.iter().zip(&baseline.pixels) {
+        let delta: u32 = a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| (*x as i32 - *y as i32).unsigned_abs())
+            .sum();
+
+        if delta as f64 > threshold {
+            differing += 1;
+            pixels.push(DIFF_HIGHLIGHT);
+        } else {
+            // Dim matching pixels so the magenta highlights stand out.
+            pixels.push([b[0] / 2, b[1] / 2, b[2] / 2, b[3]]);
+        }
+    }
+
+    let diff_ratio = differing as f64 / baseline.pixels.len() as f64;
+    if diff_ratio > max_diff_ratio {
+        Some(Image {
+            width: baseline.width,
+            height: baseline.height,
+            pixels,
+        })
+    } else {
+        None
+    }
+}
+
+/// The path a diff image is written to: `baseline` with a `.diff.png`
+/// suffix instead of its extension, next to the baseline itself.
+fn diff_image_path(baseline: &Path) -> PathBuf {
+    baseline.with_extension("diff.png")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![pixel; (width * height) as usize],
+        }
+    }
+
+    #[test]
+    fn identical_images_have_no_diff() {
+        let image = solid(2, 2, [10, 20, 30, 255]);
+        assert!(diff(&image, &image, 0.1, 0.0).is_none());
+    }
+
+    #[test]
+    fn delta_within_tolerance_has_no_diff() {
+        let baseline = solid(1, 1, [100, 100, 100, 255]);
+        let actual = solid(1, 1, [101, 100, 100, 255]);
+        assert!(diff(&actual, &baseline, 0.1, 0.0).is_none());
+    }
+
+    #[test]
+    fn delta_past_tolerance_produces_a_diff() {
+        let baseline = solid(1, 1, [0, 0, 0, 255]);
+        let actual = solid(1, 1, [255, 255, 255, 255]);
+        assert!(diff(&actual, &baseline, 0.1, 0.0).is_some());
+    }
+
+    #[test]
+    fn differing_pixel_ratio_within_max_diff_ratio_has_no_diff() {
+        let baseline = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![[0, 0, 0, 255], [0, 0, 0, 255]],
+        };
+        let actual = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![[255, 255, 255, 255], [0, 0, 0, 255]],
+        };
+        // Exactly half the pixels differ; allow up to half.
+        assert!(diff(&actual, &baseline, 0.1, 0.5).is_none());
+        assert!(diff(&actual, &baseline, 0.1, 0.4).is_some());
+    }
+
+    #[test]
+    fn mismatched_sizes_always_produce_a_diff() {
+        let baseline = solid(2, 2, [0, 0, 0, 255]);
+        let actual = solid(1, 1, [0, 0, 0, 255]);
+        let diff_image = diff(&actual, &baseline, 1.0, 1.0).expect("size mismatch always diffs");
+        assert_eq!(diff_image.width, baseline.width);
+        assert_eq!(diff_image.height, baseline.height);
+    }
+
+    #[test]
+    fn parses_a_well_formed_request() {
+        let request = Request::parse("actual.png\nbaseline.png\n0.1\n0.0").unwrap();
+        assert_eq!(request.path, "actual.png");
+        assert_eq!(request.baseline, "baseline.png");
+        assert_eq!(request.tolerance, 0.1);
+        assert_eq!(request.max_diff_ratio, 0.0);
+    }
+
+    #[test]
+    fn rejects_a_request_missing_lines() {
+        assert!(Request::parse("actual.png\nbaseline.png").is_none());
+    }
+
+    #[test]
+    fn rejects_a_request_with_unparseable_numbers() {
+        assert!(Request::parse("actual.png\nbaseline.png\nnot-a-number\n0.0").is_none());
+    }
+
+    #[test]
+    fn diff_image_path_replaces_extension() {
+        assert_eq!(
+            diff_image_path(Path::new("tests/expected/foo.png")),
+            Path::new("tests/expected/foo.diff.png"),
+        );
+    }
+}
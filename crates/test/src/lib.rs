@@ -0,0 +1,12 @@
+//! Internal runtime support for `#[wasm_bindgen_test]`.
+//!
+//! `wasm-bindgen-test-macro` expands `#[wasm_bindgen_test]` into calls
+//! against [`rt::Context`]. Test code can also call the functions in
+//! [`rt::browser`] directly: screenshots, screenshot assertions, and the
+//! console-output and failure-screenshot settings.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod rt;
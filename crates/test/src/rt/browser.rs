@@ -1,19 +1,35 @@
 //! Support for printing status information of a test suite in a browser.
 //!
-//! Currently this is quite simple, rendering the same as the console tests in
-//! node.js. Output here is rendered in a `pre`, however.
+//! Each test gets its own row with pass/fail/ignored coloring and a
+//! collapsible `<details>` section for its captured output, folded away
+//! unless it failed, so large suites are navigable instead of one giant
+//! scroll of text.
 
 use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
 use js_sys::Error;
+use wasm_bindgen::closure::Closure;
 use wasm_bindgen::prelude::*;
 
 /// Implementation of `Formatter` for browsers.
 ///
-/// Routes all output to a `pre` on the page currently. Eventually this probably
-/// wants to be a pretty table with colors and folding and whatnot.
+/// Renders a summary header plus one collapsible row per test, built up out
+/// of real DOM nodes rather than a single `pre` of appended text.
 pub struct Browser {
-    pre: Element,
+    summary: Element,
+    rows: Element,
+    /// The `<details>`/body pair for every test reported so far, in order;
+    /// `log_line` appends non-test-line output to the last row's body.
+    current_rows: RefCell<Vec<Row>>,
+}
+
+/// A single rendered test row: the `<pre>` inside its `<details>` element
+/// that captured log lines and errors are appended to.
+struct Row {
+    body: Element,
 }
 
 #[wasm_bindgen]
@@ -23,12 +39,26 @@ extern "C" {
     static DOCUMENT: HTMLDocument;
     #[wasm_bindgen(method, structural)]
     fn getElementById(this: &HTMLDocument, id: &str) -> Element;
+    #[wasm_bindgen(method, structural, js_name = createElement)]
+    fn create_element(this: &HTMLDocument, tag: &str) -> Element;
 
     type Element;
     #[wasm_bindgen(method, getter = textContent, structural)]
     fn text_content(this: &Element) -> String;
     #[wasm_bindgen(method, setter = textContent, structural)]
     fn set_text_content(this: &Element, text: &str);
+    #[wasm_bindgen(method, setter = innerHTML, structural)]
+    fn set_inner_html(this: &Element, html: &str);
+    #[wasm_bindgen(method, structural, js_name = setAttribute)]
+    fn set_attribute(this: &Element, name: &str, value: &str);
+    #[wasm_bindgen(method, structural, js_name = appendChild)]
+    fn append_child(this: &Element, child: &Element);
+    #[wasm_bindgen(method, getter, structural, js_name = classList)]
+    fn class_list(this: &Element) -> ClassList;
+
+    type ClassList;
+    #[wasm_bindgen(method, structural)]
+    fn add(this: &ClassList, name: &str);
 
     type BrowserError;
     #[wasm_bindgen(method, getter, structural)]
@@ -75,21 +105,426 @@ pub async fn screenshot(path: &str) {
     }
 }
 
+/// Default per-pixel tolerance for [`screenshot_assert`]: a pixel counts as
+/// differing once the summed absolute delta of its RGBA channels exceeds
+/// this fraction of the full `0..=1020` range.
+const DEFAULT_SCREENSHOT_TOLERANCE: f64 = 0.1;
+
+/// Default fraction of differing pixels [`screenshot_assert`] tolerates
+/// before failing the test: none.
+const DEFAULT_SCREENSHOT_MAX_DIFF_RATIO: f64 = 0.0;
+
+/// Captures a screenshot and asserts that it matches a committed baseline
+/// image, failing the test if they diverge.
+///
+/// Shorthand for [`screenshot_assert_with_tolerance`] using the default
+/// tolerance (`0.1`) and max diff ratio (`0`, i.e. no differing pixels
+/// allowed).
+pub async fn screenshot_assert(path: &str, baseline: &str) {
+    screenshot_assert_with_tolerance(
+        path,
+        baseline,
+        DEFAULT_SCREENSHOT_TOLERANCE,
+        DEFAULT_SCREENSHOT_MAX_DIFF_RATIO,
+    )
+    .await
+}
+
+/// Like [`screenshot_assert`], but with an explicit `tolerance` and
+/// `max_diff_ratio`.
+///
+/// This works like [`screenshot`]: the request (`path`, `baseline`,
+/// `tolerance`, `max_diff_ratio`) is written to the hidden
+/// `#__wbgtest_screenshot_assert` element for the headless test runner to
+/// pick up. The runner takes the screenshot, decodes both PNGs, and for each
+/// pixel sums the absolute delta of its RGBA channels; a pixel counts as
+/// "different" if that sum exceeds `tolerance` as a fraction of the full
+/// `0..=1020` range. The comparison fails if the fraction of differing
+/// pixels exceeds `max_diff_ratio`.
+///
+/// On mismatch the runner writes a diff image (differing pixels highlighted
+/// in magenta over a dimmed copy of the baseline) next to `baseline` and
+/// reports its path back through `#__wbgtest_screenshot_assert_result`,
+/// which ends up in the panic message here. Running the suite with the
+/// `UPDATE_EXPECT` environment variable set makes the runner overwrite
+/// `baseline` with the freshly captured screenshot instead of comparing
+/// against it.
+///
+/// # Panics
+///
+/// Panics if the captured screenshot differs from `baseline` by more than
+/// the given tolerances, or if the `#__wbgtest_screenshot_assert` element is
+/// not present in the page (i.e. when not running under the headless test
+/// runner).
+pub async fn screenshot_assert_with_tolerance(
+    path: &str,
+    baseline: &str,
+    tolerance: f64,
+    max_diff_ratio: f64,
+) {
+    let request = DOCUMENT.with(|doc| doc.getElementById("__wbgtest_screenshot_assert"));
+    request.set_text_content(&format!("{path}\n{baseline}\n{tolerance}\n{max_diff_ratio}"));
+
+    loop {
+        wasm_bindgen_futures::JsFuture::from(delay_promise(50))
+            .await
+            .unwrap_throw();
+
+        if request.text_content().is_empty() {
+            break;
+        }
+    }
+
+    let result = DOCUMENT.with(|doc| doc.getElementById("__wbgtest_screenshot_assert_result"));
+    let outcome = result.text_content();
+    result.set_text_content("");
+
+    if outcome != "OK" {
+        panic!("screenshot does not match baseline {baseline}, diff saved to {outcome}");
+    }
+}
+
+/// A [`RefCell`] wrapper that is `Sync` because every test target this crate
+/// supports is single-threaded; only ever accessed from the one wasm thread.
+struct AssertSync<T>(RefCell<T>);
+
+// SAFETY: wasm in a browser is single-threaded, so there is never concurrent
+// access to the inner `RefCell`.
+unsafe impl<T> Sync for AssertSync<T> {}
+
+/// Messages captured from `console.error`/`console.warn` during the
+/// currently-running test, alongside the patterns used to ignore known-noisy
+/// ones. Cleared by [`begin_console_capture`], populated by
+/// [`record_console_message`], and drained by [`end_console_capture`].
+static CONSOLE_MESSAGES: AssertSync<Vec<String>> = AssertSync(RefCell::new(Vec::new()));
+static CONSOLE_ALLOW: AssertSync<Vec<String>> = AssertSync(RefCell::new(Vec::new()));
+
+/// `console.log`/`console.info` messages captured during the
+/// currently-running test, attached to its row once the test finishes. See
+/// [`set_show_captured_output`] for when they're rendered visibly.
+static CONSOLE_LOG_MESSAGES: AssertSync<Vec<String>> = AssertSync(RefCell::new(Vec::new()));
+
+/// Whether [`set_fail_on_console_error`] has enabled the
+/// `console.error`/`console.warn` check for this suite.
+static FAIL_ON_CONSOLE_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Whether every test's captured `console.log`/`console.info` output should
+/// be shown, instead of only a failing test's.
+///
+/// Disabled by default, matching `cargo test`'s captured-stdout behavior:
+/// output only surfaces once something has gone wrong, unless this is
+/// enabled (the equivalent of `--nocapture`).
+static SHOW_ALL_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Enables failing tests that log unexpected `console.error`/`console.warn`
+/// output, with `allow` as a list of patterns for known-noisy messages (e.g.
+/// HMR or devtools warnings) to ignore.
+///
+/// Patterns are matched as plain substrings of the logged message rather
+/// than full regular expressions, to avoid pulling a regex engine into this
+/// `no_std` crate.
+///
+/// There is no `wasm_bindgen_test_configure!` arm for this; call it
+/// directly. The setting is global to the suite and stays in effect from
+/// the call onwards: messages logged earlier, including earlier in the
+/// calling test, are not checked.
+pub fn set_fail_on_console_error(allow: &[&str]) {
+    FAIL_ON_CONSOLE_ERROR.store(true, Ordering::Relaxed);
+    *CONSOLE_ALLOW.0.borrow_mut() = allow.iter().map(|s| String::from(*s)).collect();
+    // Idempotent, and `Context::new` also installs this -- but configure
+    // calls can run before a `Context` exists, so make sure the hook is in
+    // place as soon as the suite opts in.
+    install_console_capture_hook();
+}
+
+/// Always shows every test's captured `console.log`/`console.info` output in
+/// its row, rather than only a failing test's.
+pub fn set_show_captured_output(show_all: bool) {
+    SHOW_ALL_OUTPUT.store(show_all, Ordering::Relaxed);
+}
+
+/// Whether [`set_show_captured_output`] has requested every test's captured
+/// output be shown, not just a failing test's. Used by every `Formatter`,
+/// not just [`Browser`]'s own [`Browser::push_row`].
+pub(crate) fn show_all_output() -> bool {
+    SHOW_ALL_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Whether `message` matches one of the [`set_fail_on_console_error`] allow
+/// patterns, as a plain substring.
+fn is_allowed(message: &str, allow: &[String]) -> bool {
+    allow.iter().any(|pattern| message.contains(pattern.as_str()))
+}
+
+fn record_console_message(level: String, message: String) {
+    if level == "log" || level == "info" {
+        CONSOLE_LOG_MESSAGES
+            .0
+            .borrow_mut()
+            .push(format!("console.{level}: {message}"));
+        return;
+    }
+
+    if !FAIL_ON_CONSOLE_ERROR.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let allow = CONSOLE_ALLOW.0.borrow();
+    if is_allowed(&message, &allow) {
+        return;
+    }
+    drop(allow);
+
+    CONSOLE_MESSAGES
+        .0
+        .borrow_mut()
+        .push(format!("console.{level}: {message}"));
+}
+
+/// The closure installed as the console method wrapper. Kept alive for the
+/// lifetime of the program once installed, matching the usual
+/// `Closure::forget` pattern for callbacks JS holds onto indefinitely.
+static CONSOLE_RECORDER: AssertSync<Option<Closure<dyn FnMut(String, String)>>> =
+    AssertSync(RefCell::new(None));
+
+/// Installs the `console.log`/`console.info`/`console.warn`/`console.error`
+/// wrappers, forwarding to the originals so messages still show up in the
+/// real console. Idempotent: a second call is a no-op, both here and in the
+/// underlying JS.
+pub(crate) fn install_console_capture_hook() {
+    let mut recorder = CONSOLE_RECORDER.0.borrow_mut();
+    let recorder = recorder.get_or_insert_with(|| Closure::new(record_console_message));
+    install_console_capture(recorder);
+}
+
+/// Clears any console messages captured for the previous test, ready for a
+/// new one to run.
+///
+/// Called by the harness before each test body runs.
+pub(crate) fn begin_console_capture() {
+    CONSOLE_MESSAGES.0.borrow_mut().clear();
+    CONSOLE_LOG_MESSAGES.0.borrow_mut().clear();
+}
+
+/// Drains the messages captured for the just-finished test and, if any
+/// unexpected ones were logged, returns a failure message listing them.
+///
+/// Called by the harness after each test body resolves.
+pub(crate) fn end_console_capture() -> Option<String> {
+    let messages = core::mem::take(&mut *CONSOLE_MESSAGES.0.borrow_mut());
+    if messages.is_empty() {
+        return None;
+    }
+
+    let mut report = String::from("unexpected console output:\n");
+    for message in messages {
+        report.push_str("  ");
+        report.push_str(&message);
+        report.push('\n');
+    }
+    Some(report)
+}
+
+/// Drains the `console.log`/`console.info` messages captured for the
+/// just-finished test.
+pub(crate) fn take_captured_log() -> Vec<String> {
+    core::mem::take(&mut *CONSOLE_LOG_MESSAGES.0.borrow_mut())
+}
+
+#[wasm_bindgen(inline_js = "
+    export function install_console_capture(record) {
+        if (console.__wbgtest_original_error) return;
+        console.__wbgtest_original_log = console.log;
+        console.__wbgtest_original_info = console.info;
+        console.__wbgtest_original_warn = console.warn;
+        console.__wbgtest_original_error = console.error;
+        const wrap = (level, original) => function(...args) {
+            record(level, args.map(String).join(' '));
+            original.apply(console, args);
+        };
+        console.log = wrap('log', console.__wbgtest_original_log);
+        console.info = wrap('info', console.__wbgtest_original_info);
+        console.warn = wrap('warn', console.__wbgtest_original_warn);
+        console.error = wrap('error', console.__wbgtest_original_error);
+    }
+")]
+extern "C" {
+    fn install_console_capture(record: &Closure<dyn FnMut(String, String)>);
+}
+
+/// Whether a screenshot should automatically be captured when a test fails.
+///
+/// Disabled by default; toggled with [`set_auto_screenshot`].
+static AUTO_SCREENSHOT: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables automatic screenshot capture on test failure.
+///
+/// When enabled, every failing test (a panic or a returned `Err`) has a
+/// screenshot taken via the same `#__wbgtest_screenshot` mechanism as
+/// [`screenshot`], saved to a path derived from the test's module path and
+/// name. See [`failure_screenshot_path`] for how that path is built.
+pub fn set_auto_screenshot(enabled: bool) {
+    AUTO_SCREENSHOT.store(enabled, Ordering::Relaxed);
+}
+
+/// Derives the path an automatic failure screenshot for `test_name`
+/// (declared in `module_path`) is saved to:
+/// `wbg-test-failures/<crate>/<test_name>.png`.
+fn failure_screenshot_path(module_path: &str, test_name: &str) -> String {
+    let krate = module_path.split("::").next().unwrap_or(module_path);
+    format!("wbg-test-failures/{krate}/{test_name}.png")
+}
+
+/// Takes a screenshot of a failing test if automatic screenshots are
+/// enabled, returning the path it was saved to.
+///
+/// Called from the failure-reporting path right after a test panics or
+/// returns an error, so the captured image reflects the DOM at the moment
+/// of failure.
+pub(crate) async fn maybe_screenshot_on_failure(
+    module_path: &str,
+    test_name: &str,
+) -> Option<String> {
+    if !AUTO_SCREENSHOT.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let path = failure_screenshot_path(module_path, test_name);
+    screenshot(&path).await;
+    Some(path)
+}
+
+/// CSS giving each `push_row` status class its pass/fail/ignored coloring.
+///
+/// Injected once, by [`Browser::new`], as a `<style>` element rather than
+/// shipped as a separate `.css` file, since this crate has no build step to
+/// bundle one alongside the generated test HTML.
+const ROW_STYLE: &str = "
+    .wbg-test-pass { color: #2e7d32; }
+    .wbg-test-fail { color: #c62828; }
+    .wbg-test-ignored { color: #9e9e9e; }
+";
+
 impl Browser {
     /// Creates a new instance of `Browser`, assuming that its APIs will work
     /// (requires `Node::new()` to have return `None` first).
     pub fn new() -> Browser {
-        let pre = DOCUMENT.with(|document| document.getElementById("output"));
-        pre.set_text_content("");
-        Browser { pre }
+        install_console_capture_hook();
+
+        let output = DOCUMENT.with(|document| document.getElementById("output"));
+        output.set_inner_html("");
+
+        let style = DOCUMENT.with(|document| document.create_element("style"));
+        style.set_text_content(ROW_STYLE);
+        output.append_child(&style);
+
+        let summary = DOCUMENT.with(|document| document.create_element("div"));
+        summary.class_list().add("wbg-test-summary");
+        output.append_child(&summary);
+
+        let rows = DOCUMENT.with(|document| document.create_element("div"));
+        rows.class_list().add("wbg-test-rows");
+        output.append_child(&rows);
+
+        Browser {
+            summary,
+            rows,
+            current_rows: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Reports a test failure, writing the stringified error and then, if
+    /// automatic screenshots are enabled, the path of the screenshot
+    /// captured alongside it.
+    ///
+    /// This is what the failure-reporting path calls in place of a bare
+    /// `stringify_error` call, so the screenshot path (when present) shows
+    /// up right next to the error it was taken for.
+    pub(crate) async fn report_failure(&self, module_path: &str, test_name: &str, err: &JsValue) {
+        let message = super::Formatter::stringify_error(self, err);
+        self.push_output_line(&message);
+
+        if let Some(path) = maybe_screenshot_on_failure(module_path, test_name).await {
+            self.push_output_line(&format!("screenshot saved to {path}"));
+        }
+    }
+
+    /// Appends a new row for a just-finished test, folded away unless it
+    /// failed (or [`set_show_captured_output`] requested everything stay
+    /// visible), and attaches its captured `console.log`/`console.info`
+    /// output.
+    fn push_row(&self, name: &str, outcome: super::TestOutcome) {
+        let details = DOCUMENT.with(|document| document.create_element("details"));
+        let label = DOCUMENT.with(|document| document.create_element("summary"));
+        let status = match outcome {
+            super::TestOutcome::Ok => "ok",
+            super::TestOutcome::Failed => "FAILED",
+            super::TestOutcome::Ignored => "ignored",
+        };
+        label.set_text_content(&format!("{name} ... {status}"));
+        details.append_child(&label);
+
+        let class = match outcome {
+            super::TestOutcome::Ok => "wbg-test-pass",
+            super::TestOutcome::Ignored => "wbg-test-ignored",
+            super::TestOutcome::Failed => "wbg-test-fail",
+        };
+        details.class_list().add(class);
+
+        // Fold failures open by default so the developer doesn't have to
+        // click through every failing row to see what broke; otherwise only
+        // open up if the suite was configured to always show output.
+        let failed = matches!(outcome, super::TestOutcome::Failed);
+        if failed || show_all_output() {
+            details.set_attribute("open", "open");
+        }
+
+        let body = DOCUMENT.with(|document| document.create_element("pre"));
+        body.class_list().add("wbg-test-output");
+        details.append_child(&body);
+
+        self.rows.append_child(&details);
+        self.current_rows.borrow_mut().push(Row { body });
+
+        for line in take_captured_log() {
+            self.push_output_line(&line);
+        }
+    }
+
+    /// Appends a captured log line or error to the last reported row, or to
+    /// the summary header if no test has been reported yet (e.g. build
+    /// output printed before the suite starts running).
+    fn push_output_line(&self, line: &str) {
+        let target = match self.current_rows.borrow().last() {
+            Some(row) => row.body.clone(),
+            None => self.summary.clone(),
+        };
+        let mut text = target.text_content();
+        text.extend(line.chars().chain(Some('\n')));
+        target.set_text_content(&text);
     }
 }
 
 impl super::Formatter for Browser {
-    fn writeln(&self, line: &str) {
-        let mut html = self.pre.text_content();
-        html.extend(line.chars().chain(Some('\n')));
-        self.pre.set_text_content(&html);
+    fn start_suite(&self, test_count: usize) {
+        self.rows.set_inner_html("");
+        self.current_rows.borrow_mut().clear();
+        self.summary.set_text_content(&format!("running {test_count} tests"));
+    }
+
+    fn test_result(&self, name: &str, outcome: super::TestOutcome) {
+        self.push_row(name, outcome);
+    }
+
+    fn log_line(&self, line: &str) {
+        self.push_output_line(line);
+    }
+
+    fn finish_suite(&self, passed: usize, failed: usize, ignored: usize) {
+        let status = if failed == 0 { "ok" } else { "FAILED" };
+        self.summary.set_text_content(&format!(
+            "test result: {status}. {passed} passed; {failed} failed; {ignored} ignored"
+        ));
     }
 
     fn stringify_error(&self, err: &JsValue) -> String {
@@ -117,3 +552,40 @@ impl super::Formatter for Browser {
         format!("{header}\n{stack}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{failure_screenshot_path, is_allowed};
+    use alloc::string::String;
+    use alloc::vec;
+
+    #[test]
+    fn allowed_patterns_match_as_substrings() {
+        let allow = vec![String::from("DevTools"), String::from("HMR")];
+        assert!(is_allowed("some DevTools warning", &allow));
+        assert!(!is_allowed("an unrelated error", &allow));
+    }
+
+    #[test]
+    fn no_allow_patterns_matches_nothing() {
+        assert!(!is_allowed("anything at all", &[]));
+    }
+
+    #[test]
+    fn failure_screenshot_path_uses_crate_and_test_name() {
+        assert_eq!(
+            failure_screenshot_path("my_crate::tests::it_works", "it_works"),
+            "wbg-test-failures/my_crate/it_works.png",
+        );
+    }
+
+    #[test]
+    fn failure_screenshot_path_handles_unqualified_module_path() {
+        assert_eq!(
+            failure_screenshot_path("it_works", "it_works"),
+            "wbg-test-failures/it_works/it_works.png",
+        );
+    }
+}
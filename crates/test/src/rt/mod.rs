@@ -0,0 +1,176 @@
+//! Glue between a running test and whichever [`Formatter`] the suite is
+//! executing under.
+
+use alloc::string::String;
+use core::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+pub mod browser;
+pub mod node;
+
+/// How a single test finished.
+#[derive(Clone, Copy)]
+pub(crate) enum TestOutcome {
+    Ok,
+    Failed,
+    Ignored,
+}
+
+/// Where a suite's test results and logged output get reported.
+///
+/// Each method corresponds to one well-defined event in a suite's lifecycle
+/// -- as opposed to a single `writeln(&str)` sink -- so formatters build up
+/// a structured report instead of reverse-engineering one out of freeform
+/// text (which would otherwise risk misreading a failing test's own output
+/// as suite structure).
+pub(crate) trait Formatter {
+    /// Called once, before any tests run, with how many there are.
+    fn start_suite(&self, test_count: usize);
+
+    /// Called once a test has finished, with its outcome.
+    fn test_result(&self, name: &str, outcome: TestOutcome);
+
+    /// Appends a captured log line or error to the most recently reported
+    /// test.
+    fn log_line(&self, line: &str);
+
+    /// Called once every test has finished.
+    fn finish_suite(&self, passed: usize, failed: usize, ignored: usize);
+
+    /// Renders a JS error/exception thrown by a failing test.
+    fn stringify_error(&self, err: &JsValue) -> String;
+}
+
+/// The formatter a suite is reporting to, detected once at startup.
+enum Output {
+    Browser(browser::Browser),
+    Node(node::Node),
+}
+
+impl Output {
+    fn new() -> Output {
+        match node::Node::new() {
+            Some(node) => Output::Node(node),
+            None => Output::Browser(browser::Browser::new()),
+        }
+    }
+
+    fn start_suite(&self, test_count: usize) {
+        match self {
+            Output::Browser(b) => b.start_suite(test_count),
+            Output::Node(n) => n.start_suite(test_count),
+        }
+    }
+
+    fn test_result(&self, name: &str, outcome: TestOutcome) {
+        match self {
+            Output::Browser(b) => b.test_result(name, outcome),
+            Output::Node(n) => n.test_result(name, outcome),
+        }
+    }
+
+    fn log_line(&self, line: &str) {
+        match self {
+            Output::Browser(b) => b.log_line(line),
+            Output::Node(n) => n.log_line(line),
+        }
+    }
+
+    fn finish_suite(&self, passed: usize, failed: usize, ignored: usize) {
+        match self {
+            Output::Browser(b) => b.finish_suite(passed, failed, ignored),
+            Output::Node(n) => n.finish_suite(passed, failed, ignored),
+        }
+    }
+
+    /// Reports a test failure, dispatching to each formatter's own
+    /// `report_failure` so that e.g. Browser's automatic screenshot capture
+    /// (and Node's lack of one) only needs to live in one place.
+    async fn report_failure(&self, module_path: &str, test_name: &str, err: &JsValue) {
+        match self {
+            Output::Browser(b) => b.report_failure(module_path, test_name, err).await,
+            Output::Node(n) => n.report_failure(module_path, test_name, err).await,
+        }
+    }
+}
+
+/// Drives a suite's tests to completion and reports their outcomes.
+pub struct Context {
+    output: Output,
+    passed: Cell<usize>,
+    failed: Cell<usize>,
+    ignored: Cell<usize>,
+}
+
+impl Context {
+    /// Creates a new `Context`, detecting which [`Formatter`] to report
+    /// results to.
+    pub fn new() -> Context {
+        // Installed here (rather than only by `Browser::new`) because
+        // `console.error`/`console.warn`/`console.log` capture applies
+        // equally in Node, which has a `console` object too but no
+        // `Browser` formatter.
+        browser::install_console_capture_hook();
+
+        Context {
+            output: Output::new(),
+            passed: Cell::new(0),
+            failed: Cell::new(0),
+            ignored: Cell::new(0),
+        }
+    }
+
+    /// Reports how many tests are about to run.
+    pub fn start_suite(&self, test_count: usize) {
+        self.output.start_suite(test_count);
+    }
+
+    /// Reports the final pass/fail/ignored counts.
+    pub fn finish_suite(&self) {
+        self.output
+            .finish_suite(self.passed.get(), self.failed.get(), self.ignored.get());
+    }
+
+    /// Reports a test that was skipped (e.g. `#[ignore]`) without running it.
+    pub fn ignore(&self, name: &str) {
+        self.ignored.set(self.ignored.get() + 1);
+        self.output.test_result(name, TestOutcome::Ignored);
+    }
+
+    /// Runs `test` to completion, reporting its outcome to this suite's
+    /// formatter. The test fails if it returns an error or if it logged
+    /// unexpected `console.error`/`console.warn` output; a test that returns
+    /// an error also gets an automatic failure screenshot when the formatter
+    /// takes one and it's enabled. A test failed only by its console output
+    /// gets no screenshot.
+    pub async fn execute(
+        &self,
+        module_path: &str,
+        test_name: &str,
+        test: impl core::future::Future<Output = Result<(), JsValue>>,
+    ) {
+        browser::begin_console_capture();
+        let result = test.await;
+        let console_failure = browser::end_console_capture();
+
+        match (result, console_failure) {
+            (Ok(()), None) => {
+                self.passed.set(self.passed.get() + 1);
+                self.output.test_result(test_name, TestOutcome::Ok);
+            }
+            (Ok(()), Some(message)) => {
+                self.failed.set(self.failed.get() + 1);
+                self.output.test_result(test_name, TestOutcome::Failed);
+                self.output.log_line(&message);
+            }
+            (Err(err), console_failure) => {
+                self.failed.set(self.failed.get() + 1);
+                self.output.test_result(test_name, TestOutcome::Failed);
+                if let Some(message) = console_failure {
+                    self.output.log_line(&message);
+                }
+                self.output.report_failure(module_path, test_name, &err).await;
+            }
+        }
+    }
+}
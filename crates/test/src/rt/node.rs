@@ -0,0 +1,111 @@
+//! Support for printing status information of a test suite in Node.js.
+
+use alloc::format;
+use alloc::string::String;
+use js_sys::Error;
+use wasm_bindgen::prelude::*;
+
+/// Implementation of `Formatter` for Node.js.
+///
+/// There's no DOM here, so unlike [`super::browser::Browser`] this just
+/// writes lines straight to `console.log`.
+pub struct Node {}
+
+#[wasm_bindgen(inline_js = "
+    export function node_detected() {
+        return typeof process === 'object'
+            && typeof process.versions === 'object'
+            && typeof process.versions.node === 'string';
+    }
+")]
+extern "C" {
+    fn node_detected() -> bool;
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = log)]
+    fn console_log(s: &str);
+
+    type NodeError;
+    #[wasm_bindgen(method, getter, structural)]
+    fn stack(this: &NodeError) -> JsValue;
+}
+
+impl Node {
+    /// Creates a new `Node` formatter, or `None` if this isn't actually
+    /// running under Node.js (e.g. it's a browser, which should fall back to
+    /// [`super::browser::Browser`] instead).
+    pub fn new() -> Option<Node> {
+        if node_detected() {
+            Some(Node {})
+        } else {
+            None
+        }
+    }
+
+    /// Reports a test failure by writing the stringified error.
+    ///
+    /// Node has no DOM/WebDriver session to screenshot, so unlike
+    /// [`super::browser::Browser::report_failure`] this is just a thin
+    /// wrapper around `stringify_error`/`log_line`.
+    pub(crate) async fn report_failure(&self, _module_path: &str, _test_name: &str, err: &JsValue) {
+        let message = super::Formatter::stringify_error(self, err);
+        self.log_line(&message);
+    }
+}
+
+impl super::Formatter for Node {
+    fn start_suite(&self, test_count: usize) {
+        console_log(&format!("running {test_count} tests"));
+    }
+
+    fn test_result(&self, name: &str, outcome: super::TestOutcome) {
+        let status = match outcome {
+            super::TestOutcome::Ok => "ok",
+            super::TestOutcome::Failed => "FAILED",
+            super::TestOutcome::Ignored => "ignored",
+        };
+        console_log(&format!("test {name} ... {status}"));
+
+        // There's no folding here, so where Browser::push_row folds a
+        // passing test's console.log/console.info output away, it's dropped
+        // unless the suite asked to always show it, same as `cargo test`'s
+        // captured stdout.
+        let ok = matches!(outcome, super::TestOutcome::Ok);
+        let show = !ok || super::browser::show_all_output();
+        let captured = super::browser::take_captured_log();
+        if show {
+            for line in captured {
+                self.log_line(&line);
+            }
+        }
+    }
+
+    fn log_line(&self, line: &str) {
+        console_log(line);
+    }
+
+    fn finish_suite(&self, passed: usize, failed: usize, ignored: usize) {
+        let status = if failed == 0 { "ok" } else { "FAILED" };
+        console_log(&format!(
+            "test result: {status}. {passed} passed; {failed} failed; {ignored} ignored"
+        ));
+    }
+
+    fn stringify_error(&self, err: &JsValue) -> String {
+        // TODO: this should be a checked cast to `Error`
+        let err = Error::from(err.clone());
+        let name = String::from(err.name());
+        let message = String::from(err.message());
+        let err = NodeError::from(JsValue::from(err));
+        let stack = err.stack();
+
+        let header = format!("{name}: {message}");
+        match stack.as_string() {
+            Some(stack) if stack.contains(&header) => stack,
+            Some(stack) => format!("{header}\n{stack}"),
+            None => header,
+        }
+    }
+}